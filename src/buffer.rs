@@ -0,0 +1,70 @@
+//! Non-destructive peek support for SNI-based routing proxies.
+//!
+//! A router that inspects the SNI and then forwards the original
+//! ClientHello unchanged to a backend cannot afford to consume the bytes it
+//! reads for parsing. [`TeeReader`] wraps a reader and mirrors every byte it
+//! yields into an internal buffer, so the caller can later replay exactly
+//! what was already drained from the socket ahead of the live stream.
+
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{self, AsyncRead, ReadBuf};
+
+/// An [`AsyncRead`] adapter that records a copy of every byte read through
+/// it, without otherwise altering the read.
+pub struct TeeReader<'a, R> {
+    inner: Pin<&'a mut R>,
+    buffer: Vec<u8>,
+}
+
+impl<'a, R: AsyncRead> TeeReader<'a, R> {
+    /// Wrap `inner`, starting with an empty buffer.
+    pub fn new(inner: Pin<&'a mut R>) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Consume the adapter, returning every byte that was read through it.
+    pub fn into_buffer(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for TeeReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let me = self.get_mut();
+        let before = buf.filled().len();
+        ready!(me.inner.as_mut().poll_read(cx, buf))?;
+        me.buffer.extend_from_slice(&buf.filled()[before..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn buffers_exactly_what_was_read() {
+        let cursor = std::io::Cursor::new(b"a ClientHello".to_vec());
+        tokio::pin!(cursor);
+        let mut tee = TeeReader::new(cursor.as_mut());
+
+        let mut first_byte = [0u8; 1];
+        tee.read_exact(&mut first_byte).await.unwrap();
+
+        let mut rest = Vec::new();
+        tee.read_to_end(&mut rest).await.unwrap();
+
+        assert_eq!(&first_byte, b"a");
+        assert_eq!(rest, b" ClientHello");
+        assert_eq!(tee.into_buffer(), b"a ClientHello");
+    }
+}
@@ -0,0 +1,148 @@
+//! A typed error for ClientHello parsing failures.
+//!
+//! A caller deciding how to react to a failed parse needs more than "it
+//! didn't work": "the ClientHello has no SNI extension" should fall back to
+//! a default backend, while "the socket died mid-parse" should drop the
+//! connection. [`SniError`] keeps those cases distinct while still
+//! converting into [`std::io::Error`] for callers that don't care.
+
+use std::pin::Pin;
+use thiserror::Error;
+use tokio::io::{self, AsyncRead};
+
+use crate::info::read_client_hello_info_typed;
+
+/// Why parsing a ClientHello for its SNI failed.
+#[derive(Debug, Error)]
+pub enum SniError {
+    /// The handshake message wasn't a ClientHello.
+    #[error("handshake message not a ClientHello (type {got}, expected 1)")]
+    NotClientHello {
+        /// The handshake message type that was actually found.
+        got: u8,
+    },
+
+    /// The stream ended before a structure that had already started could
+    /// be finished.
+    #[error("unexpected end of stream while parsing the ClientHello")]
+    UnexpectedEof,
+
+    /// A length-prefixed field declared a length larger than the space
+    /// remaining in whatever it's nested in.
+    #[error("a length field in the ClientHello overflows its enclosing structure")]
+    LengthOverflow,
+
+    /// The SNI host name, or an ALPN protocol name, wasn't valid UTF-8.
+    #[error("SNI host name or ALPN protocol name is not valid UTF-8")]
+    NonUtf8HostName(#[source] std::string::FromUtf8Error),
+
+    /// The parse needed to read more bytes off the underlying stream than
+    /// [`crate::SniReadOptions::max_bytes`] allows.
+    #[error("ClientHello parse exceeded its {limit}-byte limit")]
+    ByteLimitExceeded {
+        /// The configured byte cap that was hit.
+        limit: u64,
+    },
+
+    /// The ClientHello declared more extensions than
+    /// [`crate::SniReadOptions::max_extensions`] allows.
+    #[error("ClientHello has more than {limit} extensions")]
+    TooManyExtensions {
+        /// The configured extension-count cap that was hit.
+        limit: usize,
+    },
+
+    /// The parse didn't finish within
+    /// [`crate::SniReadOptions::timeout`].
+    #[error("ClientHello parse timed out")]
+    Timeout,
+
+    /// Any other I/O failure reading from the underlying stream.
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for SniError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            SniError::UnexpectedEof
+        } else {
+            SniError::Io(err)
+        }
+    }
+}
+
+impl From<SniError> for io::Error {
+    fn from(err: SniError) -> Self {
+        match err {
+            SniError::Io(err) => err,
+            SniError::UnexpectedEof => io::Error::new(io::ErrorKind::UnexpectedEof, err),
+            SniError::Timeout => io::Error::new(io::ErrorKind::TimedOut, err),
+            SniError::NotClientHello { .. }
+            | SniError::LengthOverflow
+            | SniError::NonUtf8HostName(_)
+            | SniError::ByteLimitExceeded { .. }
+            | SniError::TooManyExtensions { .. } => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// Get the SNI host name from the ClientHello part of a raw TLS stream,
+/// same as [`crate::read_sni_host_name_from_client_hello`], but without
+/// collapsing "well-formed ClientHello with no SNI" and "the stream died"
+/// into the same error.
+///
+/// Returns `Ok(None)` when the ClientHello is well-formed but simply omits
+/// the SNI extension, rather than erroring.
+pub async fn try_read_sni<R: AsyncRead>(reader: Pin<&mut R>) -> Result<Option<String>, SniError> {
+    let info = read_client_hello_info_typed(reader).await?;
+    Ok(info.server_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ClientHello body with no extensions at all, i.e. no SNI.
+    fn client_hello_without_extensions() -> Vec<u8> {
+        let mut body = vec![0x03, 0x03]; // legacy_version
+        body.extend_from_slice(&[0u8; 32]); // random
+        body.push(0); // session ID, empty
+        body.extend_from_slice(&[0, 0]); // cipher suites, empty
+        body.push(0); // compression methods, empty
+        body.extend_from_slice(&[0, 0]); // extensions, empty
+
+        let mut msg = vec![1]; // handshake type: ClientHello
+        msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    #[tokio::test]
+    async fn well_formed_client_hello_without_sni_is_ok_none() {
+        let cursor = std::io::Cursor::new(client_hello_without_extensions());
+        tokio::pin!(cursor);
+
+        assert_eq!(try_read_sni(cursor.as_mut()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn stream_dying_mid_extensions_is_an_error_not_none() {
+        let mut wire = client_hello_without_extensions();
+        // Claim a 20-byte extensions block, then cut the stream off right
+        // after the extensions-length field -- same shape as a realistic
+        // mid-handshake disconnect, and not to be confused with the
+        // well-formed "no SNI" case above, which must stay `Ok(None)`.
+        let extensions_len_offset = wire.len() - 2;
+        wire[extensions_len_offset..].copy_from_slice(&20u16.to_be_bytes());
+        // Count those 20 bytes in the u24 handshake length too, so the parse
+        // runs out of stream rather than out of declared length.
+        let declared_len = (wire.len() - 4 + 20) as u32;
+        wire[1..4].copy_from_slice(&declared_len.to_be_bytes()[1..]);
+
+        let cursor = std::io::Cursor::new(wire);
+        tokio::pin!(cursor);
+
+        assert!(try_read_sni(cursor.as_mut()).await.is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! Structured ClientHello inspection.
+//!
+//! [`read_sni_host_name_from_client_hello`](crate::read_sni_host_name_from_client_hello)
+//! only ever looked at the SNI extension. Routers also need to see ALPN (to
+//! pick `h2` vs `http/1.1` backends) and the offered TLS versions (to reject
+//! obsolete ones at the edge), so [`read_client_hello_info`] walks every
+//! extension in the ClientHello and collects the ones we understand into a
+//! [`ClientHelloInfo`], skipping the rest.
+
+use crate::error::SniError;
+use crate::{read_u24, skip, skip_vec_u16, skip_vec_u8};
+use std::pin::Pin;
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+use tokio::pin;
+
+/// Handshake message type for a ClientHello.
+const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 1;
+
+/// Extension type for the Server Name Indication extension.
+const EXTENSION_TYPE_SNI: u16 = 0;
+/// Extension type for Application-Layer Protocol Negotiation.
+const EXTENSION_TYPE_ALPN: u16 = 16;
+/// Extension type for the TLS 1.3 `supported_versions` extension.
+const EXTENSION_TYPE_SUPPORTED_VERSIONS: u16 = 43;
+
+/// NameType for a DNS host name in a ServerNameList.
+const NAME_TYPE_HOST_NAME: u8 = 0;
+
+/// The routing-relevant parts of a ClientHello.
+///
+/// Any extension this crate doesn't understand is skipped rather than
+/// rejected, so unknown fields are simply absent here.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHelloInfo {
+    /// The legacy `ProtocolVersion` field at the start of the ClientHello.
+    pub legacy_version: u16,
+    /// The SNI host name, if the ServerNameList contained one.
+    pub server_name: Option<String>,
+    /// ALPN protocol names, in client preference order, if the ALPN
+    /// extension was present.
+    pub alpn_protocols: Vec<String>,
+    /// TLS versions offered via the `supported_versions` extension, in
+    /// client preference order, if present.
+    pub supported_versions: Vec<u16>,
+}
+
+/// Parse a ClientHello, collecting the SNI host name, ALPN protocol list
+/// and supported TLS versions.
+///
+/// The reader must already be positioned at the start of the handshake
+/// message (its type byte), same as
+/// [`read_sni_host_name_from_client_hello`](crate::read_sni_host_name_from_client_hello).
+pub async fn read_client_hello_info<R: AsyncRead>(
+    reader: Pin<&mut R>,
+) -> io::Result<ClientHelloInfo> {
+    read_client_hello_info_typed(reader)
+        .await
+        .map_err(Into::into)
+}
+
+pub(crate) async fn read_client_hello_info_typed<R: AsyncRead>(
+    reader: Pin<&mut R>,
+) -> Result<ClientHelloInfo, SniError> {
+    read_client_hello_info_bounded(reader, usize::MAX).await
+}
+
+/// Same as [`read_client_hello_info_typed`], but gives up with
+/// [`SniError::TooManyExtensions`] once more than `max_extensions`
+/// extensions have been iterated, guarding against a ClientHello crafted to
+/// make the extension loop spin forever.
+pub(crate) async fn read_client_hello_info_bounded<R: AsyncRead>(
+    mut reader: Pin<&mut R>,
+    max_extensions: usize,
+) -> Result<ClientHelloInfo, SniError> {
+    let typ = reader.read_u8().await?;
+    if typ != HANDSHAKE_TYPE_CLIENT_HELLO {
+        return Err(SniError::NotClientHello { got: typ });
+    }
+
+    // Handshake message length.
+    let len = read_u24(reader.as_mut()).await?;
+    let reader = reader.take(len.into());
+    pin!(reader);
+
+    // ProtocolVersion (2 bytes).
+    let legacy_version = reader.read_u16().await?;
+    // random (32 bytes).
+    skip(reader.as_mut(), 32).await?;
+
+    // Session ID (u8-length vec), cipher suites (u16-length vec), compression methods (u8-length vec).
+    skip_vec_u8(reader.as_mut()).await?;
+    skip_vec_u16(reader.as_mut()).await?;
+    skip_vec_u8(reader.as_mut()).await?;
+
+    let mut info = ClientHelloInfo {
+        legacy_version,
+        ..Default::default()
+    };
+
+    // Extensions.
+    let ext_len = reader.read_u16().await?;
+    if u64::from(ext_len) > reader.limit() {
+        return Err(SniError::LengthOverflow);
+    }
+    reader.set_limit(ext_len.into());
+    let mut ext_count = 0usize;
+    while reader.limit() > 0 {
+        if ext_count >= max_extensions {
+            return Err(SniError::TooManyExtensions {
+                limit: max_extensions,
+            });
+        }
+        ext_count += 1;
+
+        // Extension type & length.
+        let ext_typ = reader.read_u16().await?;
+        let ext_len = reader.read_u16().await?;
+        if u64::from(ext_len) > reader.limit() {
+            return Err(SniError::LengthOverflow);
+        }
+        let ext_reader = reader.as_mut().take(ext_len.into());
+        pin!(ext_reader);
+
+        match ext_typ {
+            EXTENSION_TYPE_SNI => {
+                info.server_name = read_server_name(ext_reader.as_mut()).await?;
+            }
+            EXTENSION_TYPE_ALPN => {
+                info.alpn_protocols = read_alpn_protocols(ext_reader.as_mut()).await?;
+            }
+            EXTENSION_TYPE_SUPPORTED_VERSIONS => {
+                info.supported_versions = read_supported_versions(ext_reader.as_mut()).await?;
+            }
+            _ => {}
+        }
+
+        // Skip whatever of this extension we didn't understand or didn't consume.
+        let remaining = ext_reader.limit();
+        skip(ext_reader.as_mut(), remaining).await?;
+    }
+
+    Ok(info)
+}
+
+async fn read_server_name<R: AsyncRead>(
+    mut reader: Pin<&mut R>,
+) -> Result<Option<String>, SniError> {
+    // ServerNameList length.
+    let snl_len = reader.read_u16().await?;
+    let reader = reader.take(snl_len.into());
+    pin!(reader);
+
+    loop {
+        if reader.limit() == 0 {
+            return Ok(None);
+        }
+
+        // NameType & length.
+        let name_typ = reader.read_u8().await?;
+        if name_typ != NAME_TYPE_HOST_NAME {
+            skip_vec_u16(reader.as_mut()).await?;
+            continue;
+        }
+
+        let name_len = reader.read_u16().await?;
+        if u64::from(name_len) > reader.limit() {
+            return Err(SniError::LengthOverflow);
+        }
+        let mut name_buf = vec![0; name_len.into()];
+        reader.read_exact(&mut name_buf).await?;
+        return String::from_utf8(name_buf)
+            .map(Some)
+            .map_err(SniError::NonUtf8HostName);
+    }
+}
+
+async fn read_alpn_protocols<R: AsyncRead>(
+    mut reader: Pin<&mut R>,
+) -> Result<Vec<String>, SniError> {
+    // ProtocolNameList length.
+    let list_len = reader.read_u16().await?;
+    let reader = reader.take(list_len.into());
+    pin!(reader);
+
+    let mut protocols = Vec::new();
+    while reader.limit() > 0 {
+        let proto_len = reader.read_u8().await?;
+        let mut proto_buf = vec![0; proto_len.into()];
+        reader.read_exact(&mut proto_buf).await?;
+        let proto = String::from_utf8(proto_buf).map_err(SniError::NonUtf8HostName)?;
+        protocols.push(proto);
+    }
+    Ok(protocols)
+}
+
+async fn read_supported_versions<R: AsyncRead>(
+    mut reader: Pin<&mut R>,
+) -> Result<Vec<u16>, SniError> {
+    // SupportedVersions is a u8-length vec of 2-byte ProtocolVersions.
+    let list_len = reader.read_u8().await?;
+    let reader = reader.take(list_len.into());
+    pin!(reader);
+
+    let mut versions = Vec::new();
+    while reader.limit() > 0 {
+        versions.push(reader.read_u16().await?);
+    }
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sni_extension(host: &str) -> Vec<u8> {
+        let mut name_entry = vec![NAME_TYPE_HOST_NAME];
+        name_entry.extend_from_slice(&(host.len() as u16).to_be_bytes());
+        name_entry.extend_from_slice(host.as_bytes());
+
+        let mut server_name_list = (name_entry.len() as u16).to_be_bytes().to_vec();
+        server_name_list.extend_from_slice(&name_entry);
+
+        let mut ext = EXTENSION_TYPE_SNI.to_be_bytes().to_vec();
+        ext.extend_from_slice(&(server_name_list.len() as u16).to_be_bytes());
+        ext.extend_from_slice(&server_name_list);
+        ext
+    }
+
+    fn alpn_extension(protocols: &[&str]) -> Vec<u8> {
+        let mut list = Vec::new();
+        for proto in protocols {
+            list.push(proto.len() as u8);
+            list.extend_from_slice(proto.as_bytes());
+        }
+
+        let mut body = (list.len() as u16).to_be_bytes().to_vec();
+        body.extend_from_slice(&list);
+
+        let mut ext = EXTENSION_TYPE_ALPN.to_be_bytes().to_vec();
+        ext.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        ext.extend_from_slice(&body);
+        ext
+    }
+
+    fn supported_versions_extension(versions: &[u16]) -> Vec<u8> {
+        let mut body = vec![(versions.len() * 2) as u8];
+        for version in versions {
+            body.extend_from_slice(&version.to_be_bytes());
+        }
+
+        let mut ext = EXTENSION_TYPE_SUPPORTED_VERSIONS.to_be_bytes().to_vec();
+        ext.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        ext.extend_from_slice(&body);
+        ext
+    }
+
+    fn client_hello(extensions: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x03, 0x03]; // legacy_version
+        body.extend_from_slice(&[0u8; 32]); // random
+        body.push(0); // session ID, empty
+        body.extend_from_slice(&[0, 0]); // cipher suites, empty
+        body.push(0); // compression methods, empty
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(extensions);
+
+        let mut msg = vec![HANDSHAKE_TYPE_CLIENT_HELLO];
+        msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    #[tokio::test]
+    async fn parses_sni_alpn_and_supported_versions() {
+        let mut extensions = sni_extension("example.com");
+        extensions.extend_from_slice(&alpn_extension(&["h2", "http/1.1"]));
+        extensions.extend_from_slice(&supported_versions_extension(&[0x0304, 0x0303]));
+
+        let cursor = std::io::Cursor::new(client_hello(&extensions));
+        tokio::pin!(cursor);
+
+        let info = read_client_hello_info(cursor.as_mut()).await.unwrap();
+        assert_eq!(info.server_name.as_deref(), Some("example.com"));
+        assert_eq!(
+            info.alpn_protocols,
+            vec!["h2".to_string(), "http/1.1".to_string()]
+        );
+        assert_eq!(info.supported_versions, vec![0x0304, 0x0303]);
+    }
+
+    #[tokio::test]
+    async fn client_hello_without_sni_has_no_server_name() {
+        let extensions = alpn_extension(&["http/1.1"]);
+        let cursor = std::io::Cursor::new(client_hello(&extensions));
+        tokio::pin!(cursor);
+
+        let info = read_client_hello_info(cursor.as_mut()).await.unwrap();
+        assert_eq!(info.server_name, None);
+        assert_eq!(info.alpn_protocols, vec!["http/1.1".to_string()]);
+    }
+}
@@ -2,96 +2,111 @@
 //!
 //! Extracted from <https://github.com/BranLwyd/rspd/blob/1bfad8498375f0735c229667608ddd4c23aaf7b2/src/main.rs#L367>
 
+mod buffer;
+mod error;
+mod info;
+mod limits;
+mod quic;
+mod record;
+
+pub use buffer::TeeReader;
+pub use error::{try_read_sni, SniError};
+pub use info::{read_client_hello_info, ClientHelloInfo};
+pub use limits::{try_read_sni_with_options, SniReadOptions};
+pub use quic::read_sni_from_quic_initial;
+pub use record::TlsRecordReader;
+
 use byteorder::{ByteOrder, NetworkEndian};
-use std::cmp::min;
 use std::pin::Pin;
-use tokio::io::{AsyncRead, AsyncReadExt};
-use tokio::{
-    io::{self, ErrorKind},
-    pin,
-};
+use tokio::io::{self, AsyncRead, AsyncReadExt, ErrorKind};
 
-/// Get the Server Name Indication from the ClientHello part
-/// of a raw TLS stream asynchronously.
+/// Get the Server Name Indication from a raw TCP/TLS stream asynchronously,
+/// stripping the TLS record layer first.
 ///
-/// The reader could be a `TcpStream`.
+/// The reader could be a `TcpStream` positioned at the very first byte sent
+/// by the client, i.e. the start of the first TLS record. Unlike
+/// [`read_sni_host_name_from_client_hello`], this entry point handles a
+/// ClientHello fragmented across multiple records.
 ///
-/// It'll throw if the ClientHello is not valid,
-/// or the length is invalid.
-pub async fn read_sni_host_name_from_client_hello<R: AsyncRead>(
-    mut reader: Pin<&mut R>,
+/// It'll throw if a record is not a handshake record, a record's payload
+/// exceeds the 2^14-byte cap, the reassembled ClientHello is not valid, or
+/// the records carry more handshake data than the ClientHello's own u24
+/// length declares.
+pub async fn read_sni_host_name_from_tls_stream<R: AsyncRead>(
+    reader: Pin<&mut R>,
 ) -> io::Result<String> {
-    // Handshake message type.
-    const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 1;
-    let typ = reader.read_u8().await?;
-    if typ != HANDSHAKE_TYPE_CLIENT_HELLO {
+    let mut record_reader = TlsRecordReader::new(reader);
+    let mut record_reader = Pin::new(&mut record_reader);
+    let host_name = read_sni_host_name_from_client_hello(record_reader.as_mut()).await?;
+    reject_trailing_handshake_data(record_reader).await?;
+    Ok(host_name)
+}
+
+/// Confirm the reassembled record stream has nothing left in it once the
+/// ClientHello has been consumed, rejecting records whose total reassembled
+/// length disagreed with the inner u24 handshake length.
+async fn reject_trailing_handshake_data<R: AsyncRead>(mut reader: Pin<&mut R>) -> io::Result<()> {
+    let mut probe = [0u8; 1];
+    if reader.read(&mut probe).await? != 0 {
         return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!(
-                "handshake message not a ClientHello (type {}, expected {})",
-                typ, HANDSHAKE_TYPE_CLIENT_HELLO
-            ),
+            ErrorKind::InvalidData,
+            "TLS records carried more data than the ClientHello's declared length",
         ));
     }
+    Ok(())
+}
 
-    // Handshake message length.
-    let len = read_u24(reader.as_mut()).await?;
-    let reader = reader.take(len.into());
-    pin!(reader);
-
-    // ProtocolVersion (2 bytes) & random (32 bytes).
-    skip(reader.as_mut(), 34).await?;
-
-    // Session ID (u8-length vec), cipher suites (u16-length vec), compression methods (u8-length vec).
-    skip_vec_u8(reader.as_mut()).await?;
-    skip_vec_u16(reader.as_mut()).await?;
-    skip_vec_u8(reader.as_mut()).await?;
-
-    // Extensions.
-    let ext_len = reader.read_u16().await?;
-    let new_limit = min(reader.limit(), ext_len.into());
-    reader.set_limit(new_limit);
-    loop {
-        // Extension type & length.
-        let ext_typ = reader.read_u16().await?;
-        let ext_len = reader.read_u16().await?;
-
-        const EXTENSION_TYPE_SNI: u16 = 0;
-        if ext_typ != EXTENSION_TYPE_SNI {
-            skip(reader.as_mut(), ext_len.into()).await?;
-            continue;
-        }
-        let new_limit = min(reader.limit(), ext_len.into());
-        reader.set_limit(new_limit);
-
-        // ServerNameList length.
-        let snl_len = reader.read_u16().await?;
-        let new_limit = min(reader.limit(), snl_len.into());
-        reader.set_limit(new_limit);
-
-        // ServerNameList.
-        loop {
-            // NameType & length.
-            let name_typ = reader.read_u8().await?;
-
-            const NAME_TYPE_HOST_NAME: u8 = 0;
-            if name_typ != NAME_TYPE_HOST_NAME {
-                skip_vec_u16(reader.as_mut()).await?;
-                continue;
-            }
-
-            let name_len = reader.read_u16().await?;
-            let new_limit = min(reader.limit(), name_len.into());
-            reader.set_limit(new_limit);
-            let mut name_buf = vec![0; name_len.into()];
-            reader.read_exact(&mut name_buf).await?;
-            return String::from_utf8(name_buf)
-                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err));
-        }
+/// Get the Server Name Indication from the ClientHello part of a raw TLS
+/// stream, while also returning every byte that was read off `reader` in
+/// the process.
+///
+/// This is for SNI-based routing proxies that need to forward the original
+/// ClientHello unchanged to a backend: the returned `Vec<u8>` is the exact
+/// prefix already drained from the socket, so the caller can write it
+/// followed by the rest of the live stream to the chosen upstream.
+///
+/// The host name is `None` when the ClientHello is well-formed but simply
+/// omits the SNI extension; the stream dying mid-parse is reported as an
+/// `Err` instead, since a router needs to tell those two cases apart (fall
+/// back to a default backend vs. drop the connection).
+pub async fn read_sni_and_buffer<R: AsyncRead>(
+    reader: Pin<&mut R>,
+) -> io::Result<(Option<String>, Vec<u8>)> {
+    let mut tee = TeeReader::new(reader);
+    let result = try_read_sni(Pin::new(&mut tee)).await;
+    let buffer = tee.into_buffer();
+    match result {
+        Ok(host_name) => Ok((host_name, buffer)),
+        Err(err) => Err(err.into()),
     }
 }
 
-async fn skip<R: AsyncRead>(reader: Pin<&mut R>, len: u64) -> io::Result<()> {
+/// Get the Server Name Indication from the ClientHello part
+/// of a raw TLS stream asynchronously.
+///
+/// The reader must already be positioned at the start of the handshake
+/// message, i.e. its type byte (the TLS record layer has already been
+/// stripped); see
+/// [`read_sni_host_name_from_tls_stream`] if the reader is a raw `TcpStream`.
+///
+/// This is a thin wrapper around [`read_client_hello_info`] for callers who
+/// only care about the host name.
+///
+/// It'll throw if the ClientHello is not valid,
+/// or the length is invalid.
+pub async fn read_sni_host_name_from_client_hello<R: AsyncRead>(
+    reader: Pin<&mut R>,
+) -> io::Result<String> {
+    let info = read_client_hello_info(reader).await?;
+    info.server_name.ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "ClientHello has no SNI server name extension",
+        )
+    })
+}
+
+pub(crate) async fn skip<R: AsyncRead>(reader: Pin<&mut R>, len: u64) -> io::Result<()> {
     let bytes_read = io::copy(&mut reader.take(len), &mut io::sink()).await?;
     if bytes_read < len {
         return Err(io::Error::new(
@@ -102,17 +117,17 @@ async fn skip<R: AsyncRead>(reader: Pin<&mut R>, len: u64) -> io::Result<()> {
     Ok(())
 }
 
-async fn skip_vec_u8<R: AsyncRead>(mut reader: Pin<&mut R>) -> io::Result<()> {
+pub(crate) async fn skip_vec_u8<R: AsyncRead>(mut reader: Pin<&mut R>) -> io::Result<()> {
     let sz = reader.read_u8().await?;
     skip(reader.as_mut(), sz.into()).await
 }
 
-async fn skip_vec_u16<R: AsyncRead>(mut reader: Pin<&mut R>) -> io::Result<()> {
+pub(crate) async fn skip_vec_u16<R: AsyncRead>(mut reader: Pin<&mut R>) -> io::Result<()> {
     let sz = reader.read_u16().await?;
     skip(reader.as_mut(), sz.into()).await
 }
 
-async fn read_u24<R: AsyncRead>(mut reader: Pin<&mut R>) -> io::Result<u32> {
+pub(crate) async fn read_u24<R: AsyncRead>(mut reader: Pin<&mut R>) -> io::Result<u32> {
     let mut buf = [0; 3];
     reader
         .as_mut()
@@ -120,3 +135,75 @@ async fn read_u24<R: AsyncRead>(mut reader: Pin<&mut R>) -> io::Result<u32> {
         .await
         .map(|_| NetworkEndian::read_u24(&buf))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sni_extension(host: &str) -> Vec<u8> {
+        let mut name_entry = vec![0u8]; // NameType::host_name
+        name_entry.extend_from_slice(&(host.len() as u16).to_be_bytes());
+        name_entry.extend_from_slice(host.as_bytes());
+
+        let mut server_name_list = (name_entry.len() as u16).to_be_bytes().to_vec();
+        server_name_list.extend_from_slice(&name_entry);
+
+        let mut ext = 0u16.to_be_bytes().to_vec(); // extension type: server_name
+        ext.extend_from_slice(&(server_name_list.len() as u16).to_be_bytes());
+        ext.extend_from_slice(&server_name_list);
+        ext
+    }
+
+    fn client_hello_message(host: &str) -> Vec<u8> {
+        let extensions = sni_extension(host);
+
+        let mut body = vec![0x03, 0x03]; // legacy_version
+        body.extend_from_slice(&[0u8; 32]); // random
+        body.push(0); // session ID, empty
+        body.extend_from_slice(&[0, 0]); // cipher suites, empty
+        body.push(0); // compression methods, empty
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut msg = vec![1]; // handshake type: ClientHello
+        msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    fn tls_record(payload: &[u8]) -> Vec<u8> {
+        let mut record = vec![0x16, 0x03, 0x03]; // content type: handshake
+        record.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        record.extend_from_slice(payload);
+        record
+    }
+
+    #[tokio::test]
+    async fn reads_sni_from_a_tls_stream() {
+        let wire = tls_record(&client_hello_message("example.com"));
+        let cursor = std::io::Cursor::new(wire);
+        tokio::pin!(cursor);
+
+        let host_name = read_sni_host_name_from_tls_stream(cursor.as_mut())
+            .await
+            .unwrap();
+        assert_eq!(host_name, "example.com");
+    }
+
+    #[tokio::test]
+    async fn rejects_records_carrying_more_than_the_handshake_length_declares() {
+        let mut message = client_hello_message("example.com");
+        // Extra bytes inside the record beyond what the ClientHello's own
+        // u24 handshake length says it needs.
+        message.extend_from_slice(&[0xff; 4]);
+
+        let wire = tls_record(&message);
+        let cursor = std::io::Cursor::new(wire);
+        tokio::pin!(cursor);
+
+        let err = read_sni_host_name_from_tls_stream(cursor.as_mut())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,244 @@
+//! Bounds on how much a ClientHello parse will read, iterate, or wait.
+//!
+//! Every unauthenticated client hitting a front-door demultiplexer gets to
+//! send a ClientHello, so the extension loop and the length-prefixed
+//! `read_exact`/`skip` helpers it drives can't be allowed to block forever
+//! on a slow peer or allocate off attacker-controlled u16 lengths.
+//! [`SniReadOptions`] caps total bytes consumed, caps the number of
+//! extensions iterated, and applies an overall timeout to the parse.
+
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{self, AsyncRead, ReadBuf};
+
+use crate::error::SniError;
+use crate::info::read_client_hello_info_bounded;
+
+/// Default cap on total bytes read while parsing a single ClientHello.
+const DEFAULT_MAX_BYTES: u64 = 1 << 16;
+/// Default cap on the number of extensions iterated.
+const DEFAULT_MAX_EXTENSIONS: usize = 64;
+/// Default overall time budget for a single parse.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Limits applied while parsing a single ClientHello off an unauthenticated,
+/// potentially hostile peer.
+///
+/// Used with [`crate::try_read_sni_with_options`].
+#[derive(Debug, Clone)]
+pub struct SniReadOptions {
+    max_bytes: u64,
+    max_extensions: usize,
+    timeout: Option<Duration>,
+}
+
+impl Default for SniReadOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_extensions: DEFAULT_MAX_EXTENSIONS,
+            timeout: Some(DEFAULT_TIMEOUT),
+        }
+    }
+}
+
+impl SniReadOptions {
+    /// Start from the default limits: 64 KiB, 64 extensions, 5 second
+    /// timeout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cap the total number of bytes read off the underlying reader.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Cap the number of extensions iterated before giving up.
+    pub fn max_extensions(mut self, max_extensions: usize) -> Self {
+        self.max_extensions = max_extensions;
+        self
+    }
+
+    /// Apply an overall timeout to the parse.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Disable the overall timeout, relying solely on the byte and
+    /// extension caps.
+    pub fn no_timeout(mut self) -> Self {
+        self.timeout = None;
+        self
+    }
+}
+
+/// An [`AsyncRead`] adapter that never reads more than `limit` bytes and
+/// fails any read attempted once `limit` bytes have been read through it,
+/// guarding against unbounded reads.
+///
+/// Unlike [`tokio::io::Take`], which reports a plain EOF once its limit is
+/// hit, this reports an error so the caller can tell "the peer's data ran
+/// out" apart from "we stopped trusting the peer".
+struct LimitedReader<'a, R> {
+    inner: Pin<&'a mut R>,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl<'a, R: AsyncRead> LimitedReader<'a, R> {
+    fn new(inner: Pin<&'a mut R>, limit: u64) -> Self {
+        Self {
+            inner,
+            remaining: limit,
+            exceeded: false,
+        }
+    }
+
+    fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for LimitedReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let me = self.get_mut();
+        if me.remaining == 0 {
+            me.exceeded = true;
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ClientHello parse exceeded its configured byte limit",
+            )));
+        }
+
+        let mut limited = buf.take(me.remaining as usize);
+        let filled_ptr = limited.filled().as_ptr();
+        ready!(me.inner.as_mut().poll_read(cx, &mut limited))?;
+        debug_assert_eq!(limited.filled().as_ptr(), filled_ptr);
+        let n = limited.filled().len();
+        unsafe {
+            buf.assume_init(n);
+        }
+        buf.advance(n);
+        me.remaining -= n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Get the SNI host name from the ClientHello part of a raw TLS stream,
+/// same as [`crate::try_read_sni`], but bounded by `options` so a stalled
+/// or hostile peer can't pin the caller's task indefinitely.
+pub async fn try_read_sni_with_options<R: AsyncRead>(
+    reader: Pin<&mut R>,
+    options: &SniReadOptions,
+) -> Result<Option<String>, SniError> {
+    let mut limited = LimitedReader::new(reader, options.max_bytes);
+    let parse = read_client_hello_info_bounded(Pin::new(&mut limited), options.max_extensions);
+
+    let result = match options.timeout {
+        Some(duration) => match tokio::time::timeout(duration, parse).await {
+            Ok(result) => result,
+            Err(_) => Err(SniError::Timeout),
+        },
+        None => parse.await,
+    };
+
+    match result {
+        Err(SniError::Io(_)) if limited.exceeded() => Err(SniError::ByteLimitExceeded {
+            limit: options.max_bytes,
+        }),
+        other => other.map(|info| info.server_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal well-formed ClientHello, optionally carrying `extensions`
+    /// verbatim (each must already be a complete type+length+value entry).
+    fn client_hello(extensions: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x03, 0x03]; // legacy_version
+        body.extend_from_slice(&[0u8; 32]); // random
+        body.push(0); // session ID, empty
+        body.extend_from_slice(&[0, 0]); // cipher suites, empty
+        body.push(0); // compression methods, empty
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(extensions);
+
+        let mut msg = vec![1]; // handshake type: ClientHello
+        msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    /// An unknown-to-this-crate extension, just type + zero-length value.
+    fn unknown_extension(ext_type: u16) -> Vec<u8> {
+        let mut ext = ext_type.to_be_bytes().to_vec();
+        ext.extend_from_slice(&0u16.to_be_bytes());
+        ext
+    }
+
+    struct PendingForever;
+
+    impl AsyncRead for PendingForever {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Poll::Pending
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_a_client_hello_over_the_byte_limit() {
+        let wire = client_hello(&[]);
+        let options = SniReadOptions::new().max_bytes(wire.len() as u64 - 1);
+
+        let cursor = std::io::Cursor::new(wire);
+        tokio::pin!(cursor);
+
+        let err = try_read_sni_with_options(cursor.as_mut(), &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SniError::ByteLimitExceeded { .. }));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_client_hello_over_the_extension_count_limit() {
+        let mut extensions = unknown_extension(0xff01);
+        extensions.extend_from_slice(&unknown_extension(0xff02));
+        let wire = client_hello(&extensions);
+        let options = SniReadOptions::new().max_extensions(1);
+
+        let cursor = std::io::Cursor::new(wire);
+        tokio::pin!(cursor);
+
+        let err = try_read_sni_with_options(cursor.as_mut(), &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SniError::TooManyExtensions { limit: 1 }));
+    }
+
+    #[tokio::test]
+    async fn a_stalled_peer_times_out_instead_of_hanging() {
+        let options = SniReadOptions::new().timeout(Duration::from_millis(10));
+
+        let reader = PendingForever;
+        tokio::pin!(reader);
+
+        let err = try_read_sni_with_options(reader.as_mut(), &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SniError::Timeout));
+    }
+}
@@ -0,0 +1,529 @@
+//! Extracting the SNI from a QUIC v1 Initial packet.
+//!
+//! QUIC carries its ClientHello inside CRYPTO frames of an Initial packet,
+//! encrypted with keys derived from the Destination Connection ID rather
+//! than a real shared secret (see RFC 9001 §5.2) -- this makes the
+//! ClientHello recoverable by anyone observing the packet, which is exactly
+//! what a QUIC-aware edge router needs to route on SNI the same way it
+//! would for a TCP `ClientHello` (see [`crate::read_sni_host_name_from_tls_stream`]).
+
+use aes::cipher::{BlockEncrypt, KeyInit as _};
+use aes::Aes128;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use generic_array::GenericArray;
+use hkdf::{Hkdf, HkdfExtract};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use tokio::io;
+use tokio::pin;
+
+/// `initial_salt` for QUIC v1 (RFC 9001 §5.2).
+const INITIAL_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+
+/// The only QUIC version this module knows the Initial key schedule for.
+const QUIC_VERSION_1: u32 = 1;
+
+/// Long header packet type for Initial packets.
+const LONG_PACKET_TYPE_INITIAL: u8 = 0;
+
+/// CRYPTO frame type (RFC 9000 §19.6).
+const FRAME_TYPE_CRYPTO: u64 = 0x06;
+
+/// AES-128-GCM authentication tag length, in bytes.
+const AES_GCM_TAG_LEN: usize = 16;
+
+/// Recover the SNI host name from a QUIC v1 Initial packet.
+///
+/// `datagram` is a UDP datagram as received off the wire; it may contain
+/// several QUIC packets coalesced together (RFC 9000 §12.2), in which case
+/// the leading run of Initial packets is decrypted and their CRYPTO frames
+/// are reassembled (by offset, out-of-order frames included) into a single
+/// handshake byte stream before handing it to the existing ClientHello
+/// parser. The scan stops at the first packet that isn't a decodable QUIC v1
+/// Initial packet (0-RTT, Handshake, short header, ...), since this crate
+/// never has the keys to open those; packets after it are not examined.
+///
+/// Returns `Ok(None)` if the datagram contains no recognizable QUIC v1
+/// Initial packet, or if the reassembled ClientHello has no SNI extension.
+pub async fn read_sni_from_quic_initial(datagram: &[u8]) -> io::Result<Option<String>> {
+    let mut crypto_frames = BTreeMap::new();
+    let mut offset = 0;
+    let mut saw_initial_packet = false;
+
+    while offset < datagram.len() {
+        match parse_initial_packet(&datagram[offset..], &mut crypto_frames) {
+            Ok(consumed) => {
+                saw_initial_packet = true;
+                offset += consumed;
+            }
+            // A packet we can't or don't need to decode (wrong version, not
+            // Initial, short header, ...) ends our scan: it may be a later
+            // coalesced packet we have no keys for, or trailing garbage.
+            Err(_) => break,
+        }
+    }
+
+    if !saw_initial_packet {
+        return Ok(None);
+    }
+
+    let handshake = match reassemble_crypto_stream(&crypto_frames) {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+
+    let cursor = std::io::Cursor::new(handshake);
+    pin!(cursor);
+    match crate::read_sni_host_name_from_client_hello(cursor.as_mut()).await {
+        Ok(host_name) => Ok(Some(host_name)),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Parse and decrypt a single QUIC Initial packet at the start of `packet`,
+/// folding any CRYPTO frame data it carries into `crypto_frames`. Returns
+/// the number of bytes of `packet` this QUIC packet occupied, so the caller
+/// can move on to the next coalesced packet.
+fn parse_initial_packet(
+    packet: &[u8],
+    crypto_frames: &mut BTreeMap<u64, Vec<u8>>,
+) -> io::Result<usize> {
+    // Long header: first byte, 4-byte version, at least two 1-byte CID lengths.
+    if packet.len() < 7 {
+        return Err(invalid_data("QUIC packet too short for a long header"));
+    }
+
+    let byte0 = packet[0];
+    if byte0 & 0x80 == 0 {
+        return Err(invalid_data("not a long-header QUIC packet"));
+    }
+
+    let version = u32::from_be_bytes(packet[1..5].try_into().unwrap());
+    if version != QUIC_VERSION_1 {
+        return Err(invalid_data("unsupported QUIC version"));
+    }
+
+    let packet_type = (byte0 >> 4) & 0x03;
+    if packet_type != LONG_PACKET_TYPE_INITIAL {
+        return Err(invalid_data("not a QUIC Initial packet"));
+    }
+
+    let mut pos = 5;
+    let dcid_len = packet[pos] as usize;
+    pos += 1;
+    let dcid = packet
+        .get(pos..pos + dcid_len)
+        .ok_or_else(|| invalid_data("truncated destination connection ID"))?;
+    pos += dcid_len;
+
+    let scid_len = *packet
+        .get(pos)
+        .ok_or_else(|| invalid_data("truncated source connection ID length"))? as usize;
+    pos += 1 + scid_len;
+
+    let (token_len, n) =
+        read_varint(packet.get(pos..).unwrap_or(&[])).ok_or_else(|| invalid_data("truncated token length"))?;
+    pos += n + token_len as usize;
+
+    let (payload_len, n) = read_varint(packet.get(pos..).unwrap_or(&[]))
+        .ok_or_else(|| invalid_data("truncated packet length"))?;
+    pos += n;
+
+    let pn_offset = pos;
+    let packet_end = pn_offset
+        .checked_add(payload_len as usize)
+        .filter(|&end| end <= packet.len())
+        .ok_or_else(|| invalid_data("QUIC packet length overruns the datagram"))?;
+
+    // Header protection sampling always assumes a 4-byte packet number,
+    // regardless of the packet number's real (protected) length. Both this
+    // sample and the packet number itself belong to the current packet, so
+    // they must stay within `packet_end`, not just within the datagram as a
+    // whole -- otherwise a short Initial packet coalesced with another one
+    // would read sample/packet-number bytes out of the next packet.
+    let sample_start = pn_offset + 4;
+    let sample_end = sample_start
+        .checked_add(16)
+        .filter(|&end| end <= packet_end)
+        .ok_or_else(|| invalid_data("too short to sample for header protection"))?;
+    let sample = &packet[sample_start..sample_end];
+
+    let (key, iv, hp) = derive_client_initial_keys(dcid)?;
+    let mask = header_protection_mask(&hp, sample)?;
+
+    let mut header = packet[..pn_offset].to_vec();
+    header[0] ^= mask[0] & 0x0f;
+    let pn_len = ((header[0] & 0x03) + 1) as usize;
+
+    let pn_end = pn_offset
+        .checked_add(pn_len)
+        .filter(|&end| end <= packet_end)
+        .ok_or_else(|| invalid_data("QUIC packet number overruns its own packet"))?;
+    let mut packet_number_bytes = packet[pn_offset..pn_end].to_vec();
+    for (byte, mask_byte) in packet_number_bytes.iter_mut().zip(&mask[1..1 + pn_len]) {
+        *byte ^= mask_byte;
+    }
+    header.extend_from_slice(&packet_number_bytes);
+
+    // Initial packets are exchanged before any packet loss can occur, so
+    // the wire-encoded (truncated) packet number is always the true one.
+    let packet_number = packet_number_bytes
+        .iter()
+        .fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte));
+
+    let ciphertext_start = pn_offset + pn_len;
+    let ciphertext_len = packet_end
+        .checked_sub(ciphertext_start)
+        .ok_or_else(|| invalid_data("QUIC packet length too small for its packet number"))?;
+    if ciphertext_len < AES_GCM_TAG_LEN {
+        return Err(invalid_data("QUIC packet too short for the AEAD tag"));
+    }
+    let ciphertext = &packet[ciphertext_start..packet_end];
+
+    let nonce = quic_nonce(&iv, packet_number);
+    let plaintext = decrypt_initial_payload(&key, &nonce, &header, ciphertext)?;
+
+    collect_crypto_frames(&plaintext, crypto_frames)?;
+
+    Ok(packet_end)
+}
+
+/// Derive the client's Initial packet protection key, IV and header
+/// protection key for `dcid` (RFC 9001 §5.2-5.4).
+fn derive_client_initial_keys(dcid: &[u8]) -> io::Result<([u8; 16], [u8; 12], [u8; 16])> {
+    let mut extract_ctx = HkdfExtract::<Sha256>::new(Some(&INITIAL_SALT));
+    extract_ctx.input_ikm(dcid);
+    let (_initial_secret, hk_initial) = extract_ctx.finalize();
+
+    let client_initial_secret = expand_label(&hk_initial, b"client in", 32)?;
+    let hk_client = Hkdf::<Sha256>::from_prk(&client_initial_secret)
+        .map_err(|_| invalid_data("client_initial_secret has unexpected length"))?;
+
+    let key = expand_label(&hk_client, b"quic key", 16)?;
+    let iv = expand_label(&hk_client, b"quic iv", 12)?;
+    let hp = expand_label(&hk_client, b"quic hp", 16)?;
+
+    Ok((
+        key.try_into().unwrap(),
+        iv.try_into().unwrap(),
+        hp.try_into().unwrap(),
+    ))
+}
+
+/// TLS 1.3 `HKDF-Expand-Label(secret, label, "", length)`, as reused by the
+/// QUIC key schedule (RFC 9001 §5.1, RFC 8446 §7.1).
+fn expand_label(hk: &Hkdf<Sha256>, label: &[u8], out_len: usize) -> io::Result<Vec<u8>> {
+    const LABEL_PREFIX: &[u8] = b"tls13 ";
+
+    let mut info = Vec::with_capacity(2 + 1 + LABEL_PREFIX.len() + label.len() + 1);
+    info.extend_from_slice(&(out_len as u16).to_be_bytes());
+    info.push((LABEL_PREFIX.len() + label.len()) as u8);
+    info.extend_from_slice(LABEL_PREFIX);
+    info.extend_from_slice(label);
+    info.push(0); // empty context
+
+    let mut okm = vec![0u8; out_len];
+    hk.expand(&info, &mut okm)
+        .map_err(|_| invalid_data("HKDF-Expand-Label requested an invalid output length"))?;
+    Ok(okm)
+}
+
+/// AES-128-ECB-encrypt a 16-byte header protection sample to get the mask
+/// (RFC 9001 §5.4.3).
+fn header_protection_mask(hp_key: &[u8; 16], sample: &[u8]) -> io::Result<[u8; 16]> {
+    let cipher = Aes128::new(GenericArray::from_slice(hp_key));
+    let mut block = *GenericArray::from_slice(sample);
+    cipher.encrypt_block(&mut block);
+    Ok(block.into())
+}
+
+/// Left-pad `packet_number` to 12 bytes and XOR it with the Initial IV to
+/// get the AES-GCM nonce for this packet (RFC 9001 §5.3).
+fn quic_nonce(iv: &[u8; 12], packet_number: u64) -> [u8; 12] {
+    let mut nonce = *iv;
+    let pn_bytes = packet_number.to_be_bytes();
+    for (n, p) in nonce.iter_mut().rev().zip(pn_bytes.iter().rev()) {
+        *n ^= p;
+    }
+    nonce
+}
+
+/// AES-128-GCM-decrypt an Initial packet's payload, using its (now
+/// unprotected) header as additional authenticated data.
+fn decrypt_initial_payload(
+    key: &[u8; 16],
+    nonce: &[u8; 12],
+    header: &[u8],
+    ciphertext: &[u8],
+) -> io::Result<Vec<u8>> {
+    let cipher = Aes128Gcm::new(GenericArray::from_slice(key));
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| invalid_data("QUIC Initial packet failed AEAD authentication"))
+}
+
+/// Walk the decrypted QUIC frames in `plaintext`, copying the data of every
+/// CRYPTO frame into `crypto_frames`, keyed by its stream offset.
+fn collect_crypto_frames(
+    plaintext: &[u8],
+    crypto_frames: &mut BTreeMap<u64, Vec<u8>>,
+) -> io::Result<()> {
+    let mut pos = 0;
+    while pos < plaintext.len() {
+        let (frame_type, n) = read_varint(&plaintext[pos..])
+            .ok_or_else(|| invalid_data("truncated QUIC frame type"))?;
+        pos += n;
+
+        if frame_type == 0x00 {
+            // PADDING: a single zero byte, repeated; nothing to skip past it.
+            continue;
+        }
+        if frame_type != FRAME_TYPE_CRYPTO {
+            // We don't need PING/ACK/etc. for SNI extraction, and every
+            // frame other than PADDING is self-delimiting only by type, so
+            // without a full frame table we can't safely skip an unknown
+            // one. Initial packets from real clients only ever combine
+            // CRYPTO frames with PADDING and ACK, so stop here rather than
+            // risk misparsing the rest of the packet.
+            break;
+        }
+
+        let (offset, n) =
+            read_varint(&plaintext[pos..]).ok_or_else(|| invalid_data("truncated CRYPTO offset"))?;
+        pos += n;
+        let (length, n) =
+            read_varint(&plaintext[pos..]).ok_or_else(|| invalid_data("truncated CRYPTO length"))?;
+        pos += n;
+
+        let data = plaintext
+            .get(pos..pos + length as usize)
+            .ok_or_else(|| invalid_data("truncated CRYPTO frame data"))?;
+        pos += length as usize;
+
+        crypto_frames
+            .entry(offset)
+            .or_insert_with(|| data.to_vec());
+    }
+    Ok(())
+}
+
+/// Reassemble CRYPTO frame chunks (keyed by offset, possibly out of order
+/// and overlapping) into one contiguous byte stream starting at offset 0.
+/// Returns `None` if there's a gap before any data has been seen.
+fn reassemble_crypto_stream(crypto_frames: &BTreeMap<u64, Vec<u8>>) -> Option<Vec<u8>> {
+    let mut stream = Vec::new();
+    for (&offset, chunk) in crypto_frames {
+        let offset = offset as usize;
+        if offset > stream.len() {
+            // Gap: a frame we'd need to fill it hasn't arrived (yet).
+            break;
+        }
+        let overlap = stream.len().saturating_sub(offset);
+        if overlap < chunk.len() {
+            stream.extend_from_slice(&chunk[overlap..]);
+        }
+    }
+    if stream.is_empty() {
+        None
+    } else {
+        Some(stream)
+    }
+}
+
+/// Decode a QUIC variable-length integer (RFC 9000 §16) from the start of
+/// `buf`, returning the value and the number of bytes it occupied.
+fn read_varint(buf: &[u8]) -> Option<(u64, usize)> {
+    let first = *buf.first()?;
+    let len = 1usize << (first >> 6);
+    if buf.len() < len {
+        return None;
+    }
+    let mut value = u64::from(first & 0x3f);
+    for &byte in &buf[1..len] {
+        value = (value << 8) | u64::from(byte);
+    }
+    Some((value, len))
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A QUIC Initial packet whose `Length` field (1, via a 1-byte varint)
+    /// is too small to hold even the packet number, let alone an AEAD tag,
+    /// must be rejected rather than panicking on the ciphertext slice.
+    #[test]
+    fn parse_initial_packet_rejects_truncated_length() {
+        let mut packet = vec![
+            0xc0, // long header, Initial packet type
+            0x00, 0x00, 0x00, 0x01, // version 1
+            0x08, // DCID length
+        ];
+        packet.extend_from_slice(&[0u8; 8]); // DCID
+        packet.push(0x00); // SCID length 0
+        packet.push(0x00); // token length varint: 0
+        packet.push(0x01); // Length varint: 1 (too small for the packet number)
+
+        // Pad the datagram so the 1-byte `Length` doesn't overrun it; the
+        // packet is then rejected because that `Length` leaves no room for
+        // the header-protection sample (pn_offset + 4 + 16 bytes) inside it.
+        packet.resize(packet.len() + 32, 0);
+
+        let mut crypto_frames = BTreeMap::new();
+        let result = parse_initial_packet(&packet, &mut crypto_frames);
+        assert!(result.is_err());
+    }
+
+    fn write_varint(value: u64) -> Vec<u8> {
+        if value < 64 {
+            vec![value as u8]
+        } else if value < 16384 {
+            let v = value as u16;
+            vec![0x40 | (v >> 8) as u8, (v & 0xff) as u8]
+        } else {
+            panic!("varint too large for this test helper")
+        }
+    }
+
+    fn crypto_frame(offset: u64, data: &[u8]) -> Vec<u8> {
+        let mut frame = write_varint(FRAME_TYPE_CRYPTO);
+        frame.extend(write_varint(offset));
+        frame.extend(write_varint(data.len() as u64));
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn sni_extension(host: &str) -> Vec<u8> {
+        let mut name_entry = vec![0u8]; // NameType::host_name
+        name_entry.extend_from_slice(&(host.len() as u16).to_be_bytes());
+        name_entry.extend_from_slice(host.as_bytes());
+
+        let mut server_name_list = (name_entry.len() as u16).to_be_bytes().to_vec();
+        server_name_list.extend_from_slice(&name_entry);
+
+        let mut ext = 0u16.to_be_bytes().to_vec(); // extension type: server_name
+        ext.extend_from_slice(&(server_name_list.len() as u16).to_be_bytes());
+        ext.extend_from_slice(&server_name_list);
+        ext
+    }
+
+    fn client_hello_message(host: &str) -> Vec<u8> {
+        let extensions = sni_extension(host);
+
+        let mut body = vec![0x03, 0x03]; // legacy_version
+        body.extend_from_slice(&[0u8; 32]); // random
+        body.push(0); // session ID, empty
+        body.extend_from_slice(&[0, 0]); // cipher suites, empty
+        body.push(0); // compression methods, empty
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut msg = vec![1]; // handshake type: ClientHello
+        msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    fn encrypt_payload(
+        key: &[u8; 16],
+        nonce: &[u8; 12],
+        header_aad: &[u8],
+        plaintext: &[u8],
+    ) -> Vec<u8> {
+        let cipher = Aes128Gcm::new(GenericArray::from_slice(key));
+        cipher
+            .encrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: header_aad,
+                },
+            )
+            .unwrap()
+    }
+
+    /// Build a real, correctly encrypted and header-protected QUIC v1
+    /// Initial packet carrying `plaintext` (already-framed CRYPTO data) as
+    /// its only frame content, the inverse of what [`parse_initial_packet`]
+    /// undoes.
+    fn build_initial_packet(dcid: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let pn_len = 1usize;
+        let packet_number_bytes = vec![0u8; pn_len];
+
+        let mut header = vec![0xc0 | (pn_len as u8 - 1)];
+        header.extend_from_slice(&QUIC_VERSION_1.to_be_bytes());
+        header.push(dcid.len() as u8);
+        header.extend_from_slice(dcid);
+        header.push(0); // SCID length: 0
+        header.push(0); // token length varint: 0
+
+        let payload_len = (pn_len + plaintext.len() + AES_GCM_TAG_LEN) as u64;
+        header.extend_from_slice(&write_varint(payload_len));
+
+        let pn_offset = header.len();
+        header.extend_from_slice(&packet_number_bytes);
+
+        let (key, iv, hp) = derive_client_initial_keys(dcid).unwrap();
+        let nonce = quic_nonce(&iv, 0);
+        let ciphertext = encrypt_payload(&key, &nonce, &header, plaintext);
+
+        let mut wire = header;
+        wire.extend_from_slice(&ciphertext);
+
+        let sample_start = pn_offset + 4;
+        let sample = &wire[sample_start..sample_start + 16];
+        let mask = header_protection_mask(&hp, sample).unwrap();
+
+        wire[0] ^= mask[0] & 0x0f;
+        for (byte, mask_byte) in wire[pn_offset..pn_offset + pn_len]
+            .iter_mut()
+            .zip(&mask[1..1 + pn_len])
+        {
+            *byte ^= mask_byte;
+        }
+
+        wire
+    }
+
+    #[tokio::test]
+    async fn recovers_sni_from_a_real_encrypted_initial_packet() {
+        let dcid = [0xaa; 8];
+        let plaintext = crypto_frame(0, &client_hello_message("example.com"));
+        let packet = build_initial_packet(&dcid, &plaintext);
+
+        let host_name = read_sni_from_quic_initial(&packet).await.unwrap();
+        assert_eq!(host_name.as_deref(), Some("example.com"));
+    }
+
+    #[tokio::test]
+    async fn reassembles_out_of_order_crypto_frames_within_a_packet() {
+        let dcid = [0xbb; 8];
+        let message = client_hello_message("out-of-order.example");
+        let (first, second) = message.split_at(message.len() / 2);
+
+        // The CRYPTO frame for the later offset is framed before the one
+        // for offset 0, so reassembly must sort by offset rather than by
+        // arrival order.
+        let mut plaintext = crypto_frame(first.len() as u64, second);
+        plaintext.extend(crypto_frame(0, first));
+        let packet = build_initial_packet(&dcid, &plaintext);
+
+        let host_name = read_sni_from_quic_initial(&packet).await.unwrap();
+        assert_eq!(host_name.as_deref(), Some("out-of-order.example"));
+    }
+}
@@ -0,0 +1,177 @@
+//! TLS record layer framing.
+//!
+//! A raw `TcpStream` carries the handshake wrapped in TLS records: a 5-byte
+//! header (content type, legacy version, payload length) followed by up to
+//! 2^14 bytes of payload. [`TlsRecordReader`] strips those headers and
+//! stitches the payloads of consecutive handshake records back into a single
+//! continuous byte stream, so the existing ClientHello parser can be reused
+//! unchanged on top of it.
+
+use byteorder::{ByteOrder, NetworkEndian};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{self, AsyncRead, ReadBuf};
+
+/// TLS record content type for handshake messages.
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+
+/// Maximum plaintext record payload size allowed by the TLS record layer.
+const MAX_RECORD_PAYLOAD_LEN: usize = 1 << 14;
+
+/// Record header: content type (1) + legacy version (2) + length (2).
+const RECORD_HEADER_LEN: usize = 5;
+
+enum State {
+    /// Reading the fixed-size record header into `buf[..filled]`.
+    Header { buf: [u8; RECORD_HEADER_LEN], filled: usize },
+    /// Forwarding `remaining` bytes of the current record's payload.
+    Body { remaining: usize },
+}
+
+/// An [`AsyncRead`] adapter that unwraps TLS handshake records.
+///
+/// Reading from a `TlsRecordReader` yields the reassembled handshake byte
+/// stream: record headers are consumed and validated internally rather than
+/// being visible to the caller. Any record whose content type is not
+/// handshake, or whose payload length exceeds 2^14 bytes, causes the read to
+/// fail with [`io::ErrorKind::InvalidData`].
+pub struct TlsRecordReader<'a, R> {
+    inner: Pin<&'a mut R>,
+    state: State,
+}
+
+impl<'a, R: AsyncRead> TlsRecordReader<'a, R> {
+    /// Wrap `inner` so that reads from it see a continuous handshake byte
+    /// stream instead of individual TLS records.
+    pub fn new(inner: Pin<&'a mut R>) -> Self {
+        Self {
+            inner,
+            state: State::Header { buf: [0; RECORD_HEADER_LEN], filled: 0 },
+        }
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for TlsRecordReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let me = self.get_mut();
+        loop {
+            match &mut me.state {
+                State::Header { buf: hbuf, filled } => {
+                    if *filled < RECORD_HEADER_LEN {
+                        let mut hb = ReadBuf::new(&mut hbuf[*filled..]);
+                        ready!(me.inner.as_mut().poll_read(cx, &mut hb))?;
+                        let n = hb.filled().len();
+                        if n == 0 {
+                            return Poll::Ready(if *filled == 0 {
+                                Ok(())
+                            } else {
+                                Err(io::Error::new(
+                                    io::ErrorKind::UnexpectedEof,
+                                    "stream ended mid TLS record header",
+                                ))
+                            });
+                        }
+                        *filled += n;
+                        continue;
+                    }
+
+                    let content_type = hbuf[0];
+                    if content_type != CONTENT_TYPE_HANDSHAKE {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "TLS record is not a handshake record (content type {}, expected {})",
+                                content_type, CONTENT_TYPE_HANDSHAKE
+                            ),
+                        )));
+                    }
+
+                    let len = NetworkEndian::read_u16(&hbuf[3..5]) as usize;
+                    if len > MAX_RECORD_PAYLOAD_LEN {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "TLS record payload too large ({} > {} bytes)",
+                                len, MAX_RECORD_PAYLOAD_LEN
+                            ),
+                        )));
+                    }
+
+                    me.state = State::Body { remaining: len };
+                }
+                State::Body { remaining } => {
+                    if *remaining == 0 {
+                        me.state = State::Header { buf: [0; RECORD_HEADER_LEN], filled: 0 };
+                        continue;
+                    }
+                    if buf.remaining() == 0 {
+                        return Poll::Ready(Ok(()));
+                    }
+
+                    let max = std::cmp::min(*remaining, buf.remaining());
+                    let mut sub = buf.take(max);
+                    ready!(me.inner.as_mut().poll_read(cx, &mut sub))?;
+                    let n = sub.filled().len();
+                    if n == 0 {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "stream ended mid TLS record payload",
+                        )));
+                    }
+                    unsafe {
+                        buf.assume_init(n);
+                    }
+                    buf.advance(n);
+                    *remaining -= n;
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    fn handshake_record(payload: &[u8]) -> Vec<u8> {
+        let mut record = vec![CONTENT_TYPE_HANDSHAKE, 0x03, 0x03];
+        record.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        record.extend_from_slice(payload);
+        record
+    }
+
+    #[tokio::test]
+    async fn reassembles_handshake_split_across_records() {
+        let mut wire = handshake_record(b"hello, ");
+        wire.extend_from_slice(&handshake_record(b"world"));
+
+        let cursor = std::io::Cursor::new(wire);
+        tokio::pin!(cursor);
+        let mut reader = TlsRecordReader::new(cursor.as_mut());
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, b"hello, world");
+    }
+
+    #[tokio::test]
+    async fn rejects_non_handshake_content_type() {
+        let mut wire = vec![0x17, 0x03, 0x03]; // content type 0x17 = application data
+        wire.extend_from_slice(&1u16.to_be_bytes());
+        wire.push(0x00);
+
+        let cursor = std::io::Cursor::new(wire);
+        tokio::pin!(cursor);
+        let mut reader = TlsRecordReader::new(cursor.as_mut());
+
+        let mut out = Vec::new();
+        let err = reader.read_to_end(&mut out).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}